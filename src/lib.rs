@@ -1,6 +1,7 @@
 //! # Sudoku
 //!
-//! A Sudoku solver that uses a non-recursive backtracking (depth-first search) algorithm.
+//! A Sudoku solver that uses constraint propagation (naked singles) combined with a
+//! minimum-remaining-values (MRV) backtracking search.
 //!
 //! ## Typical usage
 //!
@@ -64,6 +65,8 @@
 //! assert_eq!(4_u8, solution.as_slice()[0]);
 //! ```
 
+use rand::seq::SliceRandom;
+use rand::Rng;
 use std::convert::{From, TryInto};
 use std::fmt;
 use std::iter::{FromIterator, Iterator};
@@ -73,6 +76,9 @@ use std::str::FromStr;
 struct Bitset(u16);
 
 impl Bitset {
+    /// A [`Bitset`] with bits 1 through 9 set, representing every Sudoku digit.
+    const ALL_DIGITS: Self = Self(0b0000_0011_1111_1110);
+
     const fn new() -> Self {
         Bitset(0)
     }
@@ -84,6 +90,19 @@ impl Bitset {
     fn set(&mut self, index: u8) {
         self.0 |= 1 << index;
     }
+
+    fn clear(&mut self, index: u8) {
+        self.0 &= !(1 << index);
+    }
+
+    const fn count_ones(self) -> u32 {
+        self.0.count_ones()
+    }
+
+    /// Returns an iterator over the indices of the set bits, in ascending order.
+    fn iter(self) -> impl Iterator<Item = u8> {
+        (0..16).filter(move |i| self.is_set(*i))
+    }
 }
 
 /// Check whether the given set of numbers violates the rules of Sudoku i.e. contains a repeated
@@ -160,31 +179,434 @@ impl Grid {
         Self { board: *input }
     }
 
+    /// Parses a `Grid` from the sparse coordinate format used by some hand-entered or
+    /// database-exported puzzles: a header line `9,9` followed by one `row,col,value` triple per
+    /// line, listing only the filled cells (0-indexed row/column, 1-9 value). Cells that are never
+    /// listed are left unfilled (`0`).
+    ///
+    /// Returns `Err` if the header is missing or wrong, a triple is malformed or out of range, the
+    /// same cell is listed more than once, or the resulting grid violates the rules of Sudoku.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use sudoku::Grid;
+    ///
+    /// let puzzle = Grid::from_coordinates(
+    ///     "9,9\n\
+    ///      0,2,3\n\
+    ///      0,4,2\n\
+    ///      0,6,6",
+    /// )
+    /// .unwrap();
+    /// assert_eq!(3, puzzle.as_slice()[2]);
+    /// ```
+    pub fn from_coordinates(input: &str) -> Result<Self, String> {
+        let mut lines = input.lines();
+        let header = lines
+            .next()
+            .ok_or_else(|| "Missing header line".to_string())?
+            .trim();
+        if header != "9,9" {
+            return Err(format!("Expected header \"9,9\", got \"{}\"", header));
+        }
+
+        let mut board = [0_u8; 81];
+        let mut filled = [false; 81];
+        for line in lines {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let mut fields = line.split(',');
+            let mut next_field = |name: &str| -> Result<&str, String> {
+                fields
+                    .next()
+                    .map(str::trim)
+                    .ok_or_else(|| format!("Missing {} in line: \"{}\"", name, line))
+            };
+            let row: usize = next_field("row")?
+                .parse()
+                .map_err(|_| format!("Invalid row in line: \"{}\"", line))?;
+            let col: usize = next_field("column")?
+                .parse()
+                .map_err(|_| format!("Invalid column in line: \"{}\"", line))?;
+            let value: u8 = next_field("value")?
+                .parse()
+                .map_err(|_| format!("Invalid value in line: \"{}\"", line))?;
+            if fields.next().is_some() {
+                return Err(format!("Too many fields in line: \"{}\"", line));
+            }
+            if row >= 9 || col >= 9 {
+                return Err(format!(
+                    "Row and column must be in 0..9, got ({}, {})",
+                    row, col
+                ));
+            }
+            if !(1..=9).contains(&value) {
+                return Err(format!("Value must be in 1..=9, got {}", value));
+            }
+
+            let idx = row * 9 + col;
+            if filled[idx] {
+                return Err(format!(
+                    "Cell ({}, {}) was filled more than once",
+                    row, col
+                ));
+            }
+            filled[idx] = true;
+            board[idx] = value;
+        }
+
+        let grid = Self { board };
+        if !grid.is_legal() {
+            return Err("Grid violates the rules of Sudoku".to_string());
+        }
+        Ok(grid)
+    }
+
     /// Returns a solution to the given `Grid`, if one exists.
     ///
     /// `solve()` copies out the solution into a new `Grid` object. It returns the first solution
     /// found, even if multiple solutions may exist. If no solution exists, it returns [`None`].
+    ///
+    /// Internally, this repeatedly fills in "naked singles" (cells with only one remaining
+    /// candidate digit) and otherwise branches on the most-constrained empty cell (the one with
+    /// the fewest candidates), maintaining per-row/column/box candidate sets incrementally rather
+    /// than re-scanning the whole board after every placement.
     pub fn solve(&self) -> Option<Self> {
-        const ALL_SUDOKU_DIGITS: [u8; 9] = [1, 2, 3, 4, 5, 6, 7, 8, 9];
+        if !self.is_legal() {
+            return None;
+        }
+
+        let mut board = self.board;
+        let (mut row_used, mut col_used, mut box_used) = Self::used_sets(&board);
+        if Self::solve_helper(&mut board, &mut row_used, &mut col_used, &mut box_used) {
+            Some(Self { board })
+        } else {
+            None
+        }
+    }
+
+    /// Generates a random, fully solved [`Grid`] and removes `clues_to_remove` clues from it,
+    /// using the thread-local RNG. The resulting puzzle is guaranteed to have exactly one
+    /// solution; see [`Self::generate_with_rng`] for a seedable variant.
+    pub fn generate(clues_to_remove: usize) -> Self {
+        Self::generate_with_rng(clues_to_remove, &mut rand::thread_rng())
+    }
+
+    /// Same as [`Self::generate`], but draws randomness from the given `rng` so that the result
+    /// is reproducible.
+    ///
+    /// Clues are removed one at a time, in a random order, keeping a removal only if the puzzle
+    /// still has a unique solution afterwards. As a result, fewer than `clues_to_remove` clues may
+    /// end up being removed if no further removal preserves uniqueness.
+    pub fn generate_with_rng<R: Rng + ?Sized>(clues_to_remove: usize, rng: &mut R) -> Self {
+        let mut puzzle = Self::random_solution(rng);
+
+        let mut cell_order: Vec<usize> = (0..81).collect();
+        cell_order.shuffle(rng);
+
+        let mut removed = 0;
+        for idx in cell_order {
+            if removed >= clues_to_remove {
+                break;
+            }
+            let digit = puzzle.board[idx];
+            puzzle.board[idx] = 0;
+            if puzzle.has_unique_solution() {
+                removed += 1;
+            } else {
+                puzzle.board[idx] = digit;
+            }
+        }
+        puzzle
+    }
+
+    /// Returns a fully solved, randomly generated `Grid`.
+    fn random_solution<R: Rng + ?Sized>(rng: &mut R) -> Self {
+        let mut board = [0_u8; 81];
+        let mut row_used = [Bitset::new(); 9];
+        let mut col_used = [Bitset::new(); 9];
+        let mut box_used = [Bitset::new(); 9];
+        let filled = Self::fill_randomly(&mut board, &mut row_used, &mut col_used, &mut box_used, rng);
+        debug_assert!(filled, "a randomized fill of an empty grid should always succeed");
+        Self { board }
+    }
+
+    /// Recursively fills empty cells of `board` with a random, legal assignment, branching on the
+    /// most-constrained cell and trying its candidates in a random order. Returns `true` once
+    /// every cell is filled.
+    fn fill_randomly<R: Rng + ?Sized>(
+        board: &mut [u8; 81],
+        row_used: &mut [Bitset; 9],
+        col_used: &mut [Bitset; 9],
+        box_used: &mut [Bitset; 9],
+        rng: &mut R,
+    ) -> bool {
+        let idx = match Self::most_constrained_cell(board, row_used, col_used, box_used) {
+            Some(idx) => idx,
+            None => return true,
+        };
+        let (row, col) = (idx / 9, idx % 9);
+        let mut digits: Vec<u8> = Self::candidates(row_used, col_used, box_used, row, col)
+            .iter()
+            .collect();
+        digits.shuffle(rng);
+        for digit in digits {
+            Self::place(board, row_used, col_used, box_used, idx, digit);
+            if Self::fill_randomly(board, row_used, col_used, box_used, rng) {
+                return true;
+            }
+            Self::unplace(board, row_used, col_used, box_used, idx, digit);
+        }
+        false
+    }
+
+    /// Returns `true` if the puzzle has exactly one solution.
+    fn has_unique_solution(&self) -> bool {
+        if !self.is_legal() {
+            return false;
+        }
+
+        let mut board = self.board;
+        let (mut row_used, mut col_used, mut box_used) = Self::used_sets(&board);
+        let mut solution_count = 0;
+        Self::count_solutions(
+            &mut board,
+            &mut row_used,
+            &mut col_used,
+            &mut box_used,
+            &mut solution_count,
+            2,
+        );
+        solution_count == 1
+    }
+
+    /// Builds the per-row/column/box bitsets of digits already present in `board`.
+    fn used_sets(board: &[u8; 81]) -> ([Bitset; 9], [Bitset; 9], [Bitset; 9]) {
+        let mut row_used = [Bitset::new(); 9];
+        let mut col_used = [Bitset::new(); 9];
+        let mut box_used = [Bitset::new(); 9];
+        for (idx, &digit) in board.iter().enumerate() {
+            if digit != 0 {
+                let (row, col) = (idx / 9, idx % 9);
+                row_used[row].set(digit);
+                col_used[col].set(digit);
+                box_used[Self::box_index(row, col)].set(digit);
+            }
+        }
+        (row_used, col_used, box_used)
+    }
+
+    /// Index of the 3x3 box containing the given row and column.
+    const fn box_index(row: usize, col: usize) -> usize {
+        (row / 3) * 3 + col / 3
+    }
+
+    /// Returns the set of digits that are still legal to place at `(row, col)`, given the digits
+    /// already used in its row, column, and box.
+    fn candidates(
+        row_used: &[Bitset; 9],
+        col_used: &[Bitset; 9],
+        box_used: &[Bitset; 9],
+        row: usize,
+        col: usize,
+    ) -> Bitset {
+        let used =
+            row_used[row].0 | col_used[col].0 | box_used[Self::box_index(row, col)].0;
+        Bitset(Bitset::ALL_DIGITS.0 & !used)
+    }
+
+    /// Places `digit` at `idx`, updating the row/column/box candidate-tracking bitsets.
+    fn place(
+        board: &mut [u8; 81],
+        row_used: &mut [Bitset; 9],
+        col_used: &mut [Bitset; 9],
+        box_used: &mut [Bitset; 9],
+        idx: usize,
+        digit: u8,
+    ) {
+        let (row, col) = (idx / 9, idx % 9);
+        board[idx] = digit;
+        row_used[row].set(digit);
+        col_used[col].set(digit);
+        box_used[Self::box_index(row, col)].set(digit);
+    }
+
+    /// Reverses [`Self::place`], clearing `idx` back to 0.
+    fn unplace(
+        board: &mut [u8; 81],
+        row_used: &mut [Bitset; 9],
+        col_used: &mut [Bitset; 9],
+        box_used: &mut [Bitset; 9],
+        idx: usize,
+        digit: u8,
+    ) {
+        let (row, col) = (idx / 9, idx % 9);
+        board[idx] = 0;
+        row_used[row].clear(digit);
+        col_used[col].clear(digit);
+        box_used[Self::box_index(row, col)].clear(digit);
+    }
 
-        let mut boards_to_check = Vec::<Self>::with_capacity(100);
-        boards_to_check.push(*self);
-        while let Some(mut board) = boards_to_check.pop() {
-            if board.is_solved() {
-                return Some(board);
+    /// Returns the index of the empty cell with the fewest remaining candidates (the
+    /// most-constrained cell), or [`None`] if the board has no empty cells left.
+    fn most_constrained_cell(
+        board: &[u8; 81],
+        row_used: &[Bitset; 9],
+        col_used: &[Bitset; 9],
+        box_used: &[Bitset; 9],
+    ) -> Option<usize> {
+        let mut best: Option<(usize, u32)> = None;
+        for (idx, &digit) in board.iter().enumerate() {
+            if digit != 0 {
+                continue;
+            }
+            let (row, col) = (idx / 9, idx % 9);
+            let count = Self::candidates(row_used, col_used, box_used, row, col).count_ones();
+            let is_better = match best {
+                Some((_, best_count)) => count < best_count,
+                None => true,
+            };
+            if is_better {
+                best = Some((idx, count));
+            }
+        }
+        best.map(|(idx, _)| idx)
+    }
+
+    /// Recursively solves `board` in place using naked-single propagation followed by MRV
+    /// backtracking. Returns `true` if a solution was found, in which case `board` holds it.
+    /// Returns `false` if no solution exists, in which case `board` and the candidate bitsets are
+    /// left exactly as they were given.
+    fn solve_helper(
+        board: &mut [u8; 81],
+        row_used: &mut [Bitset; 9],
+        col_used: &mut [Bitset; 9],
+        box_used: &mut [Bitset; 9],
+    ) -> bool {
+        let mut filled = Vec::new();
+
+        loop {
+            let mut progress = false;
+            for idx in 0..81 {
+                if board[idx] != 0 {
+                    continue;
+                }
+                let (row, col) = (idx / 9, idx % 9);
+                let candidates = Self::candidates(row_used, col_used, box_used, row, col);
+                match candidates.count_ones() {
+                    0 => {
+                        for &idx in filled.iter().rev() {
+                            let digit = board[idx];
+                            Self::unplace(board, row_used, col_used, box_used, idx, digit);
+                        }
+                        return false;
+                    }
+                    1 => {
+                        let digit = candidates.iter().next().unwrap();
+                        Self::place(board, row_used, col_used, box_used, idx, digit);
+                        filled.push(idx);
+                        progress = true;
+                    }
+                    _ => {}
+                }
+            }
+            if !progress {
+                break;
+            }
+        }
+
+        let idx = match Self::most_constrained_cell(board, row_used, col_used, box_used) {
+            Some(idx) => idx,
+            None => return true,
+        };
+        let (row, col) = (idx / 9, idx % 9);
+        let candidates = Self::candidates(row_used, col_used, box_used, row, col);
+        for digit in candidates.iter() {
+            Self::place(board, row_used, col_used, box_used, idx, digit);
+            if Self::solve_helper(board, row_used, col_used, box_used) {
+                return true;
+            }
+            Self::unplace(board, row_used, col_used, box_used, idx, digit);
+        }
+
+        for &idx in filled.iter().rev() {
+            let digit = board[idx];
+            Self::unplace(board, row_used, col_used, box_used, idx, digit);
+        }
+        false
+    }
+
+    /// Counts solutions of `board` up to `limit`, adding them to `solution_count`. Stops exploring
+    /// further branches as soon as `limit` is reached, since callers only care whether the count
+    /// is 0, 1, or "more than one". Leaves `board` and the candidate bitsets as they were given.
+    fn count_solutions(
+        board: &mut [u8; 81],
+        row_used: &mut [Bitset; 9],
+        col_used: &mut [Bitset; 9],
+        box_used: &mut [Bitset; 9],
+        solution_count: &mut u32,
+        limit: u32,
+    ) {
+        if *solution_count >= limit {
+            return;
+        }
+
+        let mut filled = Vec::new();
+
+        loop {
+            let mut progress = false;
+            for idx in 0..81 {
+                if board[idx] != 0 {
+                    continue;
+                }
+                let (row, col) = (idx / 9, idx % 9);
+                let candidates = Self::candidates(row_used, col_used, box_used, row, col);
+                match candidates.count_ones() {
+                    0 => {
+                        for &idx in filled.iter().rev() {
+                            let digit = board[idx];
+                            Self::unplace(board, row_used, col_used, box_used, idx, digit);
+                        }
+                        return;
+                    }
+                    1 => {
+                        let digit = candidates.iter().next().unwrap();
+                        Self::place(board, row_used, col_used, box_used, idx, digit);
+                        filled.push(idx);
+                        progress = true;
+                    }
+                    _ => {}
+                }
+            }
+            if !progress {
+                break;
             }
-            // TODO: Speed up the DFS by finding the zero with the least possible digits (as
-            // checked by is_legal. Can also fill in zeros that only have one possibility along the
-            // way.
-            let first_zero_idx = board.board.iter().position(|i| *i == 0_u8).unwrap();
-            for digit in &ALL_SUDOKU_DIGITS {
-                board.board[first_zero_idx] = *digit;
-                if board.is_legal() {
-                    boards_to_check.push(board);
+        }
+
+        match Self::most_constrained_cell(board, row_used, col_used, box_used) {
+            None => *solution_count += 1,
+            Some(idx) => {
+                let (row, col) = (idx / 9, idx % 9);
+                let candidates = Self::candidates(row_used, col_used, box_used, row, col);
+                for digit in candidates.iter() {
+                    Self::place(board, row_used, col_used, box_used, idx, digit);
+                    Self::count_solutions(board, row_used, col_used, box_used, solution_count, limit);
+                    Self::unplace(board, row_used, col_used, box_used, idx, digit);
+                    if *solution_count >= limit {
+                        break;
+                    }
                 }
             }
         }
-        None
+
+        for &idx in filled.iter().rev() {
+            let digit = board[idx];
+            Self::unplace(board, row_used, col_used, box_used, idx, digit);
+        }
     }
 
     /// Returns a slice over the elements in the `Grid`. The elements are returned in the same
@@ -193,10 +615,6 @@ impl Grid {
         &self.board
     }
 
-    fn is_solved(&self) -> bool {
-        !self.board.contains(&0_u8)
-    }
-
     fn is_legal(&self) -> bool {
         /// Indices of the top left corners of each box of nine squares in a sudoku puzzle
         const NINTHS_IDXS: [usize; 9] = [0, 3, 6, 27, 30, 33, 54, 57, 60];
@@ -299,6 +717,7 @@ impl PartialEq<[u8; 81]> for Grid {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rand::SeedableRng;
 
     #[test]
     fn test_is_set_legal() {
@@ -377,4 +796,61 @@ mod tests {
         ]);
         assert!(known_bad_puzzle.solve().is_none());
     }
+
+    #[test]
+    fn generate_has_unique_solvable_puzzle() {
+        let mut rng = rand::thread_rng();
+        let puzzle = Grid::generate_with_rng(40, &mut rng);
+        assert!(puzzle.has_unique_solution());
+        assert!(puzzle.solve().is_some());
+    }
+
+    #[test]
+    fn generate_is_reproducible_with_same_seed() {
+        let mut rng_a = rand::rngs::StdRng::seed_from_u64(42);
+        let mut rng_b = rand::rngs::StdRng::seed_from_u64(42);
+        let puzzle_a = Grid::generate_with_rng(40, &mut rng_a);
+        let puzzle_b = Grid::generate_with_rng(40, &mut rng_b);
+        assert_eq!(puzzle_a, puzzle_b);
+    }
+
+    #[test]
+    fn from_coordinates_fills_only_listed_cells() {
+        let puzzle = Grid::from_coordinates(
+            "9,9\n\
+             0,2,3\n\
+             0,4,2\n\
+             0,6,6",
+        )
+        .expect("Parsing error");
+        let mut expected = [0_u8; 81];
+        expected[2] = 3;
+        expected[4] = 2;
+        expected[6] = 6;
+        assert_eq!(puzzle, expected);
+    }
+
+    #[test]
+    fn from_coordinates_rejects_bad_header() {
+        assert!(Grid::from_coordinates("not a header").is_err());
+    }
+
+    #[test]
+    fn from_coordinates_rejects_out_of_range_indices() {
+        assert!(Grid::from_coordinates("9,9\n9,0,1").is_err());
+        assert!(Grid::from_coordinates("9,9\n0,9,1").is_err());
+        assert!(Grid::from_coordinates("9,9\n0,0,0").is_err());
+        assert!(Grid::from_coordinates("9,9\n0,0,10").is_err());
+    }
+
+    #[test]
+    fn from_coordinates_rejects_duplicate_cell() {
+        assert!(Grid::from_coordinates("9,9\n0,0,1\n0,0,2").is_err());
+    }
+
+    #[test]
+    fn from_coordinates_rejects_contradictory_input() {
+        // Two givens of "1" in the same row.
+        assert!(Grid::from_coordinates("9,9\n0,0,1\n0,1,1").is_err());
+    }
 }